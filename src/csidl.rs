@@ -0,0 +1,213 @@
+//! `SHGetKnownFolderPath` doesn't exist before Vista, and some
+//! `KNOWNFOLDERID`s aren't implemented by older or alternative shells (e.g.
+//! Wine). Where a legacy `CSIDL` equivalent exists, we can retry the lookup
+//! through `SHGetFolderPathW` instead.
+
+use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::PathBuf, ptr::null_mut};
+
+use winapi::{
+    shared::winerror::{S_FALSE, S_OK},
+    um::shlobj::{
+        SHGetFolderPathW, CSIDL_ADMINTOOLS, CSIDL_APPDATA, CSIDL_BITBUCKET,
+        CSIDL_CDBURN_AREA, CSIDL_COMMON_ADMINTOOLS, CSIDL_COMMON_APPDATA,
+        CSIDL_COMMON_DESKTOPDIRECTORY, CSIDL_COMMON_DOCUMENTS, CSIDL_COMMON_MUSIC,
+        CSIDL_COMMON_OEM_LINKS, CSIDL_COMMON_PICTURES, CSIDL_COMMON_PROGRAMS,
+        CSIDL_COMMON_STARTMENU, CSIDL_COMMON_STARTUP, CSIDL_COMMON_TEMPLATES,
+        CSIDL_COMMON_VIDEO, CSIDL_CONNECTIONS, CSIDL_CONTROLS, CSIDL_COOKIES, CSIDL_DESKTOPDIRECTORY,
+        CSIDL_DRIVES, CSIDL_FAVORITES, CSIDL_FONTS, CSIDL_HISTORY, CSIDL_INTERNET,
+        CSIDL_INTERNET_CACHE, CSIDL_LOCAL_APPDATA, CSIDL_MYMUSIC, CSIDL_MYPICTURES,
+        CSIDL_MYVIDEO, CSIDL_NETHOOD, CSIDL_NETWORK, CSIDL_PERSONAL, CSIDL_PRINTERS,
+        CSIDL_PRINTHOOD, CSIDL_PROFILE, CSIDL_PROFILES, CSIDL_PROGRAMS,
+        CSIDL_PROGRAM_FILES, CSIDL_PROGRAM_FILESX86, CSIDL_PROGRAM_FILES_COMMON,
+        CSIDL_PROGRAM_FILES_COMMONX86, CSIDL_RECENT, CSIDL_RESOURCES,
+        CSIDL_RESOURCES_LOCALIZED, CSIDL_SENDTO, CSIDL_STARTMENU, CSIDL_STARTUP, CSIDL_SYSTEM,
+        CSIDL_SYSTEMX86, CSIDL_TEMPLATES, CSIDL_WINDOWS,
+    },
+    um::winnt::HANDLE,
+};
+
+use crate::Error;
+
+/// `CSIDL_*` equivalent for each `FolderId`, in the same order as
+/// `FOLDER_IDS`. `None` where no CSIDL equivalent exists (mostly folders
+/// introduced on Windows 7+ that have no pre-Vista counterpart).
+pub(crate) static CSIDL_IDS: &[Option<i32>] = &[
+    Some(CSIDL_NETWORK),                    // NetworkFolder
+    Some(CSIDL_DRIVES),                      // ComputerFolder
+    Some(CSIDL_INTERNET),                    // InternetFolder
+    Some(CSIDL_CONTROLS),                    // ControlPanelFolder
+    Some(CSIDL_PRINTERS),                    // PrintersFolder
+    None,                                     // SyncManagerFolder
+    None,                                     // SyncSetupFolder
+    None,                                     // ConflictFolder
+    None,                                     // SyncResultsFolder
+    Some(CSIDL_BITBUCKET),                   // RecycleBinFolder
+    Some(CSIDL_CONNECTIONS),                 // ConnectionsFolder
+    Some(CSIDL_FONTS),                        // Fonts
+    Some(CSIDL_DESKTOPDIRECTORY),             // Desktop
+    Some(CSIDL_STARTUP),                      // Startup
+    Some(CSIDL_PROGRAMS),                     // Programs
+    Some(CSIDL_STARTMENU),                    // StartMenu
+    Some(CSIDL_RECENT),                       // Recent
+    Some(CSIDL_SENDTO),                       // SendTo
+    Some(CSIDL_PERSONAL),                     // Documents
+    Some(CSIDL_FAVORITES),                    // Favorites
+    Some(CSIDL_NETHOOD),                      // NetHood
+    Some(CSIDL_PRINTHOOD),                    // PrintHood
+    Some(CSIDL_TEMPLATES),                    // Templates
+    Some(CSIDL_COMMON_STARTUP),               // CommonStartup
+    Some(CSIDL_COMMON_PROGRAMS),              // CommonPrograms
+    Some(CSIDL_COMMON_STARTMENU),             // CommonStartMenu
+    Some(CSIDL_COMMON_DESKTOPDIRECTORY),      // PublicDesktop
+    Some(CSIDL_COMMON_APPDATA),               // ProgramData
+    Some(CSIDL_COMMON_TEMPLATES),             // CommonTemplates
+    Some(CSIDL_COMMON_DOCUMENTS),             // PublicDocuments
+    Some(CSIDL_APPDATA),                      // RoamingAppData
+    Some(CSIDL_LOCAL_APPDATA),                // LocalAppData
+    None,                                     // LocalAppDataLow
+    Some(CSIDL_INTERNET_CACHE),                // InternetCache
+    Some(CSIDL_COOKIES),                      // Cookies
+    Some(CSIDL_HISTORY),                      // History
+    Some(CSIDL_SYSTEM),                       // System
+    Some(CSIDL_SYSTEMX86),                    // SystemX86
+    Some(CSIDL_WINDOWS),                      // Windows
+    Some(CSIDL_PROFILE),                      // Profile
+    Some(CSIDL_MYPICTURES),                   // Pictures
+    Some(CSIDL_PROGRAM_FILESX86),             // ProgramFilesX86
+    Some(CSIDL_PROGRAM_FILES_COMMONX86),      // ProgramFilesCommonX86
+    None,                                     // ProgramFilesX64
+    None,                                     // ProgramFilesCommonX64
+    Some(CSIDL_PROGRAM_FILES),                // ProgramFiles
+    Some(CSIDL_PROGRAM_FILES_COMMON),         // ProgramFilesCommon
+    None,                                     // UserProgramFiles
+    None,                                     // UserProgramFilesCommon
+    Some(CSIDL_ADMINTOOLS),                   // AdminTools
+    Some(CSIDL_COMMON_ADMINTOOLS),            // CommonAdminTools
+    Some(CSIDL_MYMUSIC),                      // Music
+    Some(CSIDL_MYVIDEO),                      // Videos
+    None,                                     // Ringtones
+    Some(CSIDL_COMMON_PICTURES),               // PublicPictures
+    Some(CSIDL_COMMON_MUSIC),                  // PublicMusic
+    Some(CSIDL_COMMON_VIDEO),                  // PublicVideos
+    None,                                     // PublicRingtones
+    Some(CSIDL_RESOURCES),                     // ResourceDir
+    Some(CSIDL_RESOURCES_LOCALIZED),           // LocalizedResourcesDir
+    Some(CSIDL_COMMON_OEM_LINKS),              // CommonOEMLinks
+    Some(CSIDL_CDBURN_AREA),                   // CDBurning
+    Some(CSIDL_PROFILES),                      // UserProfiles
+    None,                                     // Playlists
+    None,                                     // SamplePlaylists
+    None,                                     // SampleMusic
+    None,                                     // SamplePictures
+    None,                                     // SampleVideos
+    None,                                     // PhotoAlbums
+    None,                                     // Public
+    None,                                     // ChangeRemovePrograms
+    None,                                     // AppUpdates
+    None,                                     // AddNewPrograms
+    None,                                     // Downloads
+    None,                                     // PublicDownloads
+    None,                                     // SavedSearches
+    None,                                     // QuickLaunch
+    None,                                     // Contacts
+    None,                                     // SidebarParts
+    None,                                     // SidebarDefaultParts
+    None,                                     // PublicGameTasks
+    None,                                     // GameTasks
+    None,                                     // SavedGames
+    None,                                     // Games
+    None,                                     // SearchMapi
+    None,                                     // SearchCsc
+    None,                                     // Links
+    None,                                     // UsersFiles
+    None,                                     // UsersLibraries
+    None,                                     // SearchHome
+    None,                                     // OriginalImages
+    None,                                     // DocumentsLibrary
+    None,                                     // MusicLibrary
+    None,                                     // PicturesLibrary
+    None,                                     // VideosLibrary
+    None,                                     // RecordedTVLibrary
+    None,                                     // HomeGroup
+    None,                                     // HomeGroupCurrentUser
+    None,                                     // DeviceMetadataStore
+    None,                                     // Libraries
+    None,                                     // PublicLibraries
+    None,                                     // UserPinned
+    None,                                     // ImplicitAppShortcuts
+    None,                                     // AccountPictures
+    None,                                     // PublicUserTiles
+    None,                                     // AppsFolder
+    None,                                     // StartMenuAllPrograms
+    None,                                     // CommonStartMenuPlaces
+    None,                                     // ApplicationShortcuts
+    None,                                     // RoamingTiles
+    None,                                     // RoamedTileImages
+    None,                                     // Screenshots
+    None,                                     // CameraRoll
+    None,                                     // SkyDrive
+    None,                                     // OneDrive
+    None,                                     // SkyDriveDocuments
+    None,                                     // SkyDrivePictures
+    None,                                     // SkyDriveMusic
+    None,                                     // SkyDriveCameraRoll
+    None,                                     // SearchHistory
+    None,                                     // SearchTemplates
+    None,                                     // CameraRollLibrary
+    None,                                     // SavedPictures
+    None,                                     // SavedPicturesLibrary
+    None,                                     // RetailDemo
+    None,                                     // Device
+    None,                                     // DevelopmentFiles
+    None,                                     // Objects3D
+    None,                                     // AppCaptures
+    None,                                     // LocalDocuments
+    None,                                     // LocalPictures
+    None,                                     // LocalVideos
+    None,                                     // LocalMusic
+    None,                                     // LocalDownloads
+    None,                                     // RecordedCalls
+    None,                                     // AllAppMods
+    None,                                     // CurrentAppMods
+    None,                                     // AppDataDesktop
+    None,                                     // AppDataDocuments
+    None,                                     // AppDataFavorites
+    None,                                     // AppDataProgramData
+];
+
+const MAX_PATH: usize = 260;
+
+/// `SHGetKnownFolderPath` HRESULTs that indicate the function itself (or the
+/// requested `KNOWNFOLDERID`) simply isn't implemented on this shell, as
+/// opposed to a real lookup failure that happens to hit the generic
+/// [`Error::Other`] arm (access denied, RPC failure, out of memory, ...).
+/// Of the HRESULTs that end up in [`Error::Other`], only these warrant
+/// retrying through the legacy `SHGetFolderPathW`; [`Error::NotFound`] is
+/// retried as well.
+pub(crate) fn is_not_implemented(hr: u32) -> bool {
+    const E_NOTIMPL: u32 = 0x80004001;
+    const REGDB_E_CLASSNOTREG: u32 = 0x80040154;
+    matches!(hr, E_NOTIMPL | REGDB_E_CLASSNOTREG)
+}
+
+/// Resolve `csidl` via the legacy `SHGetFolderPathW`, for shells that don't
+/// implement `SHGetKnownFolderPath` (pre-Vista Windows, and some versions of
+/// Wine).
+pub(crate) fn raw_csidl_path(csidl: i32, token: HANDLE) -> Result<PathBuf, Error> {
+    let mut buf = [0u16; MAX_PATH];
+    let hr = unsafe { SHGetFolderPathW(null_mut(), csidl, token, 0, buf.as_mut_ptr()) };
+    // S_FALSE means the CSIDL resolves to a valid path that doesn't exist on
+    // disk yet (a common case on Wine and fresh profiles). It is reported as
+    // NotFound rather than Other: it doesn't call SetLastError, so
+    // last_os_error() would be stale garbage here.
+    if hr == S_FALSE {
+        return Err(Error::NotFound);
+    }
+    if hr != S_OK {
+        return Err(Error::Other(hr as u32, std::io::Error::last_os_error()));
+    }
+
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    let os_str: OsString = OsStringExt::from_wide(&buf[..len]);
+    Ok(PathBuf::from(os_str))
+}
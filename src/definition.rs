@@ -0,0 +1,284 @@
+use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::PathBuf, ptr::null_mut};
+
+use winapi::{
+    shared::guiddef::GUID,
+    shared::winerror::{S_FALSE, S_OK},
+    um::{
+        combaseapi::{
+            CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_INPROC_SERVER,
+        },
+        objbase::COINIT_APARTMENTTHREADED,
+        shlobj::FreeKnownFolderDefinitionFields,
+        shobjidl_core::{
+            CLSID_KnownFolderManager, IKnownFolder, IKnownFolderManager, KNOWNFOLDER_DEFINITION,
+        },
+        shtypes::REFKNOWNFOLDERID,
+        winbase::lstrlenW,
+        winnt::PWSTR,
+    },
+    Interface,
+};
+
+use crate::Error;
+
+/// A known folder's GUID and the canonical (shell-internal) name it's
+/// registered under, as yielded by enumeration.
+#[derive(Debug, Clone)]
+pub struct KnownFolderInfo {
+    pub id: GUID,
+    pub name: Option<String>,
+}
+
+/// The `KF_CATEGORY` a known folder belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderCategory {
+    Virtual,
+    Fixed,
+    Common,
+    PerUser,
+}
+
+impl FolderCategory {
+    fn from_raw(value: winapi::um::shobjidl_core::KF_CATEGORY) -> Self {
+        use winapi::um::shobjidl_core::*;
+        match value {
+            KF_CATEGORY_VIRTUAL => FolderCategory::Virtual,
+            KF_CATEGORY_FIXED => FolderCategory::Fixed,
+            KF_CATEGORY_COMMON => FolderCategory::Common,
+            _ => FolderCategory::PerUser,
+        }
+    }
+}
+
+/// Full metadata for a known folder, as reported by
+/// `IKnownFolder::GetFolderDefinition`. Unlike [`crate::known_folder_path`],
+/// this can be obtained for virtual folders too, since it doesn't require
+/// the folder to resolve to a real path.
+#[derive(Debug, Clone)]
+pub struct FolderDefinition {
+    pub category: FolderCategory,
+    /// The known folder this one is nested under, if any.
+    pub parent: Option<GUID>,
+    /// The path segment(s) under `parent`. On Windows 8 and later this may
+    /// list more than one alternative, which is why it's a `Vec` rather than
+    /// a single `PathBuf`.
+    pub relative_path: Vec<PathBuf>,
+    pub parsing_name: Option<String>,
+    pub attributes: u32,
+}
+
+fn is_null_guid(guid: &GUID) -> bool {
+    guid.Data1 == 0 && guid.Data2 == 0 && guid.Data3 == 0 && guid.Data4 == [0; 8]
+}
+
+unsafe fn pwstr_to_string(ptr: PWSTR) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let len = lstrlenW(ptr) as usize;
+    let slice = std::slice::from_raw_parts(ptr, len);
+    Some(OsString::from_wide(slice).to_string_lossy().into_owned())
+}
+
+/// `pszRelativePath` is a single string on pre-Win8, but may be a
+/// double-null-terminated list of alternative relative paths on Windows 8+.
+/// Either way, walking null-terminated runs until an empty one is hit covers
+/// both shapes.
+unsafe fn pwstr_to_path_list(ptr: PWSTR) -> Vec<PathBuf> {
+    if ptr.is_null() {
+        return Vec::new();
+    }
+
+    let mut paths = Vec::new();
+    let mut cursor = ptr;
+    loop {
+        let len = lstrlenW(cursor) as usize;
+        if len == 0 {
+            break;
+        }
+        let slice = std::slice::from_raw_parts(cursor, len);
+        paths.push(PathBuf::from(OsString::from_wide(slice)));
+        cursor = cursor.add(len + 1);
+    }
+    paths
+}
+
+fn hresult_err(hr: i32) -> Error {
+    Error::Other(hr as u32, std::io::Error::last_os_error())
+}
+
+/// `CoCreateInstance` requires COM to be initialized on the calling thread,
+/// which a plain console/service process won't have done on its own. We
+/// initialize it for the duration of the call and undo that on drop — but
+/// only if our `CoInitializeEx` call succeeded (`S_OK`, or `S_FALSE` when COM
+/// was already initialized on this thread; either must be balanced by a
+/// `CoUninitialize`); if COM was already initialized with an
+/// incompatible concurrency model (`RPC_E_CHANGED_MODE`) we leave it alone.
+struct ComGuard(bool);
+
+impl ComGuard {
+    unsafe fn acquire() -> Self {
+        let hr = CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED);
+        ComGuard(hr == S_OK || hr == S_FALSE)
+    }
+}
+
+impl Drop for ComGuard {
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { CoUninitialize() };
+        }
+    }
+}
+
+unsafe fn open_manager() -> Result<*mut IKnownFolderManager, Error> {
+    let mut manager: *mut IKnownFolderManager = null_mut();
+    let hr = CoCreateInstance(
+        &CLSID_KnownFolderManager,
+        null_mut(),
+        CLSCTX_INPROC_SERVER,
+        &IKnownFolderManager::uuidof(),
+        &mut manager as *mut _ as *mut _,
+    );
+    if hr != S_OK || manager.is_null() {
+        return Err(hresult_err(hr));
+    }
+    Ok(manager)
+}
+
+/// Consumes (releases) `folder` and returns a [`FolderDefinition`] built
+/// from its `KNOWNFOLDER_DEFINITION`.
+unsafe fn definition_from_folder(folder: *mut IKnownFolder) -> Result<FolderDefinition, Error> {
+    let mut def: KNOWNFOLDER_DEFINITION = std::mem::zeroed();
+    let hr = (*folder).GetFolderDefinition(&mut def);
+    (*folder).Release();
+    if hr != S_OK {
+        return Err(hresult_err(hr));
+    }
+
+    let result = FolderDefinition {
+        category: FolderCategory::from_raw(def.category),
+        parent: if is_null_guid(&def.fidParent) {
+            None
+        } else {
+            Some(def.fidParent)
+        },
+        relative_path: pwstr_to_path_list(def.pszRelativePath),
+        parsing_name: pwstr_to_string(def.pszParsingName),
+        attributes: def.dwAttributes,
+    };
+
+    FreeKnownFolderDefinitionFields(&mut def);
+
+    Ok(result)
+}
+
+pub(crate) fn raw_folder_definition(id: REFKNOWNFOLDERID) -> Result<FolderDefinition, Error> {
+    unsafe {
+        let _com = ComGuard::acquire();
+        let manager = open_manager()?;
+
+        let mut folder: *mut IKnownFolder = null_mut();
+        let hr = (*manager).GetFolder(id, &mut folder);
+        (*manager).Release();
+        if hr != S_OK || folder.is_null() {
+            return Err(hresult_err(hr));
+        }
+
+        definition_from_folder(folder)
+    }
+}
+
+/// Converts a legacy `CSIDL` value into the `KNOWNFOLDERID` GUID that
+/// replaced it, via `IKnownFolderManager::FolderIdFromCsidl`.
+pub(crate) fn raw_folder_id_from_csidl(csidl: i32) -> Result<GUID, Error> {
+    unsafe {
+        let _com = ComGuard::acquire();
+        let manager = open_manager()?;
+        let mut id: GUID = std::mem::zeroed();
+        let hr = (*manager).FolderIdFromCsidl(csidl, &mut id);
+        (*manager).Release();
+        if hr != S_OK {
+            return Err(hresult_err(hr));
+        }
+        Ok(id)
+    }
+}
+
+/// Looks up the known-folder definition for a legacy `CSIDL` value, via
+/// `IKnownFolderManager::FolderIdFromCsidl` followed by `GetFolder` — there
+/// is no single-step CSIDL-to-`IKnownFolder` method on the real interface.
+pub(crate) fn raw_folder_definition_from_csidl(csidl: i32) -> Result<FolderDefinition, Error> {
+    unsafe {
+        let _com = ComGuard::acquire();
+        let manager = open_manager()?;
+
+        let mut id: GUID = std::mem::zeroed();
+        let hr = (*manager).FolderIdFromCsidl(csidl, &mut id);
+        if hr != S_OK {
+            (*manager).Release();
+            return Err(hresult_err(hr));
+        }
+
+        let mut folder: *mut IKnownFolder = null_mut();
+        let hr = (*manager).GetFolder(&id, &mut folder);
+        (*manager).Release();
+        if hr != S_OK || folder.is_null() {
+            return Err(hresult_err(hr));
+        }
+        definition_from_folder(folder)
+    }
+}
+
+/// Lists every known folder registered with the shell, via
+/// `IKnownFolderManager::GetFolderIds`.
+pub(crate) fn raw_enumerate_known_folders() -> Result<Vec<KnownFolderInfo>, Error> {
+    unsafe {
+        let _com = ComGuard::acquire();
+        let manager = open_manager()?;
+
+        let mut ids: *mut GUID = null_mut();
+        let mut count: u32 = 0;
+        let hr = (*manager).GetFolderIds(&mut ids, &mut count);
+        if hr != S_OK {
+            (*manager).Release();
+            return Err(hresult_err(hr));
+        }
+
+        if count == 0 || ids.is_null() {
+            if !ids.is_null() {
+                CoTaskMemFree(ids as *mut _);
+            }
+            (*manager).Release();
+            return Ok(Vec::new());
+        }
+
+        let id_slice = std::slice::from_raw_parts(ids, count as usize);
+        let mut result = Vec::with_capacity(id_slice.len());
+        for id in id_slice {
+            let mut folder: *mut IKnownFolder = null_mut();
+            let hr = (*manager).GetFolder(id, &mut folder);
+            if hr != S_OK || folder.is_null() {
+                result.push(KnownFolderInfo { id: *id, name: None });
+                continue;
+            }
+
+            let mut def: KNOWNFOLDER_DEFINITION = std::mem::zeroed();
+            let hr = (*folder).GetFolderDefinition(&mut def);
+            (*folder).Release();
+            if hr != S_OK {
+                result.push(KnownFolderInfo { id: *id, name: None });
+                continue;
+            }
+            let name = pwstr_to_string(def.pszName);
+            FreeKnownFolderDefinitionFields(&mut def);
+
+            result.push(KnownFolderInfo { id: *id, name });
+        }
+
+        CoTaskMemFree(ids as *mut _);
+        (*manager).Release();
+
+        Ok(result)
+    }
+}
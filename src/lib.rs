@@ -1,15 +1,31 @@
 #![cfg(windows)]
 
+mod csidl;
+mod definition;
+
+pub use definition::{FolderCategory, FolderDefinition, KnownFolderInfo};
+
 use std::{
-    ffi::OsString, fmt::Display, os::windows::ffi::OsStringExt, path::PathBuf, ptr::null_mut,
+    ffi::OsString,
+    fmt::Display,
+    os::windows::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    ptr::null_mut,
 };
 
 use winapi::{
     shared::guiddef::GUID,
     shared::winerror::{E_FAIL, E_INVALIDARG, HRESULT, S_OK},
     um::{
-        combaseapi::CoTaskMemFree, knownfolders::*, shlobj::SHGetKnownFolderPath,
-        shtypes::REFKNOWNFOLDERID, winbase::lstrlenW, winnt::PWSTR,
+        combaseapi::CoTaskMemFree,
+        knownfolders::*,
+        shlobj::{
+            SHGetKnownFolderPath, SHSetKnownFolderPath, KF_FLAG_CREATE, KF_FLAG_DEFAULT_PATH,
+            KF_FLAG_DONT_VERIFY, KF_FLAG_INIT, KF_FLAG_NO_ALIAS,
+        },
+        shtypes::REFKNOWNFOLDERID,
+        winbase::lstrlenW,
+        winnt::{HANDLE, PWSTR},
     },
 };
 
@@ -18,6 +34,7 @@ pub enum Error {
     Virtual,
     NotFound,
     InvalidArg(std::io::Error),
+    CreateFailed(std::io::Error),
     Other(u32, std::io::Error),
 }
 
@@ -27,6 +44,7 @@ impl Display for Error {
             Error::Virtual => "virtual folders have no path",
             Error::NotFound => "not found",
             Error::InvalidArg(_) => "invalid arg",
+            Error::CreateFailed(_) => "failed to create folder",
             Error::Other(_, _) => "other",
         })
     }
@@ -37,9 +55,30 @@ impl std::error::Error for Error {}
 const NOT_FOUND: HRESULT = 0x80070002u32 as i32;
 const CANNOT_FIND_PATH: HRESULT = 0x80070003u32 as i32;
 
-fn raw_known_folder_path(id: REFKNOWNFOLDERID) -> Result<PathBuf, Error> {
+bitflags::bitflags! {
+    /// Maps to the `KF_FLAG_*` bitset accepted by `SHGetKnownFolderPath`'s
+    /// `dwFlags` argument.
+    pub struct KnownFolderFlags: u32 {
+        /// Create the folder if it doesn't already exist.
+        const CREATE = KF_FLAG_CREATE;
+        /// Skip the legacy file-system-redirection verification step.
+        const DONT_VERIFY = KF_FLAG_DONT_VERIFY;
+        /// Return the default (non-redirected) path instead of the current one.
+        const DEFAULT_PATH = KF_FLAG_DEFAULT_PATH;
+        /// Return the non-aliased path, bypassing any IDList alias resolution.
+        const NO_ALIAS = KF_FLAG_NO_ALIAS;
+        /// Force initialization of the folder if it's not yet been initialized.
+        const INIT = KF_FLAG_INIT;
+    }
+}
+
+fn raw_known_folder_path(
+    id: REFKNOWNFOLDERID,
+    flags: KnownFolderFlags,
+    token: HANDLE,
+) -> Result<PathBuf, Error> {
     let mut ptr: PWSTR = null_mut();
-    let ret = unsafe { SHGetKnownFolderPath(id, 0, null_mut(), &mut ptr) };
+    let ret = unsafe { SHGetKnownFolderPath(id, flags.bits(), token, &mut ptr) };
     let result = match ret {
         S_OK => {
             let len = unsafe { lstrlenW(ptr) } as usize;
@@ -49,8 +88,17 @@ fn raw_known_folder_path(id: REFKNOWNFOLDERID) -> Result<PathBuf, Error> {
         }
         E_FAIL => Err(Error::Virtual),
         E_INVALIDARG => Err(Error::InvalidArg(std::io::Error::last_os_error())),
-        NOT_FOUND | CANNOT_FIND_PATH => Err(Error::NotFound),
+        NOT_FOUND | CANNOT_FIND_PATH => {
+            if flags.contains(KnownFolderFlags::CREATE) {
+                Err(Error::CreateFailed(std::io::Error::last_os_error()))
+            } else {
+                Err(Error::NotFound)
+            }
+        }
         // E_NOTFOUND => Err(Error::NotFound(std::io::Error::last_os_error())),
+        e if flags.contains(KnownFolderFlags::CREATE) => {
+            Err(Error::CreateFailed(std::io::Error::last_os_error()))
+        }
         e => Err(Error::Other(e as u32, std::io::Error::last_os_error())),
     };
 
@@ -62,7 +110,127 @@ fn raw_known_folder_path(id: REFKNOWNFOLDERID) -> Result<PathBuf, Error> {
 
 #[inline(always)]
 pub fn known_folder_path(id: FolderId) -> Result<PathBuf, Error> {
-    raw_known_folder_path(&FOLDER_IDS[id as usize])
+    known_folder_path_with(id, KnownFolderFlags::empty())
+}
+
+/// Like [`known_folder_path`], but with full control over the `KF_FLAG_*`
+/// bitset passed to `SHGetKnownFolderPath`. Pass [`KnownFolderFlags::CREATE`]
+/// to have Windows create the directory if it doesn't exist yet, in which
+/// case a failure to do so is reported as [`Error::CreateFailed`] rather
+/// than [`Error::NotFound`].
+pub fn known_folder_path_with(id: FolderId, flags: KnownFolderFlags) -> Result<PathBuf, Error> {
+    known_folder_path_for_token(id, null_mut(), flags)
+}
+
+/// Resolve a known folder for the user identified by `token` rather than the
+/// calling process's user. `token` should be a primary or impersonation
+/// access token for the target user, as obtained from e.g. `LogonUser` or
+/// `DuplicateTokenEx`.
+///
+/// This is most useful with profile-relative folders such as
+/// [`FolderId::RoamingAppData`], [`FolderId::LocalAppData`],
+/// [`FolderId::Documents`], and especially [`FolderId::Profile`], which
+/// yields the target user's home directory.
+pub fn known_folder_path_for_token(
+    id: FolderId,
+    token: HANDLE,
+    flags: KnownFolderFlags,
+) -> Result<PathBuf, Error> {
+    let index = id as usize;
+    let result = raw_known_folder_path(&FOLDER_IDS[index], flags, token);
+    let should_fall_back = match &result {
+        Err(Error::NotFound) => true,
+        Err(Error::Other(hr, _)) => csidl::is_not_implemented(*hr),
+        _ => false,
+    };
+    if should_fall_back {
+        if let Some(csidl) = csidl::CSIDL_IDS[index] {
+            return csidl::raw_csidl_path(csidl, token);
+        }
+    }
+    result
+}
+
+/// Fetch the full `KNOWNFOLDER_DEFINITION` for `id` via `IKnownFolderManager`,
+/// rather than just its resolved path. This works for virtual folders too,
+/// since it doesn't need to resolve anything to a real filesystem path.
+pub fn folder_definition(id: FolderId) -> Result<FolderDefinition, Error> {
+    definition::raw_folder_definition(&FOLDER_IDS[id as usize])
+}
+
+fn raw_set_known_folder_path(
+    id: REFKNOWNFOLDERID,
+    flags: KnownFolderFlags,
+    token: HANDLE,
+    path: &Path,
+) -> Result<(), Error> {
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let ret = unsafe { SHSetKnownFolderPath(id, flags.bits(), token, wide.as_ptr()) };
+    match ret {
+        S_OK => Ok(()),
+        E_INVALIDARG => Err(Error::InvalidArg(std::io::Error::last_os_error())),
+        e => Err(Error::Other(e as u32, std::io::Error::last_os_error())),
+    }
+}
+
+/// Redirect a known folder to `path`, as with `SHSetKnownFolderPath`.
+///
+/// Only folders in the `PerUser` category can be redirected; for anything
+/// else this returns [`Error::InvalidArg`], mirroring the `E_INVALIDARG` that
+/// `SHSetKnownFolderPath` itself returns for non-redirectable folders.
+#[inline(always)]
+pub fn set_known_folder_path(id: FolderId, path: &Path) -> Result<(), Error> {
+    set_known_folder_path_with(id, path, KnownFolderFlags::empty())
+}
+
+/// Like [`set_known_folder_path`], but with full control over the
+/// `KF_FLAG_*` bitset passed to `SHSetKnownFolderPath`.
+#[inline(always)]
+pub fn set_known_folder_path_with(
+    id: FolderId,
+    path: &Path,
+    flags: KnownFolderFlags,
+) -> Result<(), Error> {
+    set_known_folder_path_for_token(id, null_mut(), path, flags)
+}
+
+/// Like [`set_known_folder_path_with`], but redirects the folder for the
+/// user identified by `token` rather than the calling process's user.
+pub fn set_known_folder_path_for_token(
+    id: FolderId,
+    token: HANDLE,
+    path: &Path,
+    flags: KnownFolderFlags,
+) -> Result<(), Error> {
+    raw_set_known_folder_path(&FOLDER_IDS[id as usize], flags, token, path)
+}
+
+/// Resolve an arbitrary `KNOWNFOLDERID`, rather than one of the ones listed
+/// in [`FolderId`]. Useful for non-published folder GUIDs such as
+/// `FOLDERID_CryptoKeys`, or any GUID discovered at runtime via
+/// [`enumerate_known_folders`].
+pub fn known_folder_path_by_guid(guid: &GUID, flags: KnownFolderFlags) -> Result<PathBuf, Error> {
+    raw_known_folder_path(guid, flags, null_mut())
+}
+
+/// Lists every known folder the system knows about, each with its GUID and
+/// canonical shell name (e.g. `"AddNewProgramsFolder"`), via
+/// `IKnownFolderManager::GetFolderIds`.
+pub fn enumerate_known_folders() -> Result<Vec<KnownFolderInfo>, Error> {
+    definition::raw_enumerate_known_folders()
+}
+
+/// Converts a legacy `CSIDL` value into the `KNOWNFOLDERID` GUID that
+/// replaced it, via `IKnownFolderManager::FolderIdFromCsidl`.
+pub fn folder_id_from_csidl(csidl: i32) -> Result<GUID, Error> {
+    definition::raw_folder_id_from_csidl(csidl)
+}
+
+/// Looks up the known-folder definition for a legacy `CSIDL` value, via
+/// `IKnownFolderManager::FolderIdFromCsidl` followed by `GetFolder` — there
+/// is no single-step CSIDL-to-`IKnownFolder` method on the real interface.
+pub fn folder_definition_from_csidl(csidl: i32) -> Result<FolderDefinition, Error> {
+    definition::raw_folder_definition_from_csidl(csidl)
 }
 
 pub enum FolderId {
@@ -358,7 +526,11 @@ mod tests {
     #[test]
     fn all_ids() {
         for (i, id) in super::FOLDER_IDS.iter().enumerate() {
-            let path = super::raw_known_folder_path(id);
+            let path = super::raw_known_folder_path(
+                id,
+                super::KnownFolderFlags::empty(),
+                std::ptr::null_mut(),
+            );
             match path {
                 Ok(path) => println!("{}: {}", i, path.display()),
                 Err(err) => println!("{}: {}", i, err),